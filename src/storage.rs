@@ -1,13 +1,37 @@
-use crate::models::{Config, ImageFormat, LocalConfig, Output, PageList, Pagination, TargetFile};
+use crate::models::{
+    Config, CosConfig, ImageFormat, LocalConfig, Output, PageList, Pagination, TargetFile,
+};
+use async_trait::async_trait;
 use log::info;
+use s3::bucket::Bucket;
+use s3::creds::Credentials;
+use s3::region::Region;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::convert::From;
 use std::error::Error;
-use std::fs;
-use std::fs::{create_dir_all, read_dir, File};
-use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::PathBuf;
+use std::fs::create_dir_all;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter, ReadBuf};
+
+/// Extensions `import_dir` recognizes as images.
+const IMPORT_EXTENSIONS: &[&str] = &["png", "jpeg", "jpg", "webp", "gif"];
+
+/// Outcome of a bulk `import_dir` call.
+#[derive(Default)]
+pub struct ImportSummary {
+    /// Files that were ingested into the partition.
+    pub succeeded: Vec<PathBuf>,
+    /// Files that were not images, or whose hash already existed in the partition.
+    pub skipped: Vec<PathBuf>,
+    /// Files that failed to import, with the error each one hit.
+    pub failed: Vec<(PathBuf, String)>,
+}
 
 /// Key: the resolve's name, for example: xs, s, m, origin.
 /// Value: The url of a resolve.
@@ -20,64 +44,212 @@ pub struct Scheme {
     pub pictures: Pictures,
 }
 
+/// A picture found via a conditional fetch (see `Storage::get_picture_conditional`).
+pub enum PictureResponse {
+    /// The caller's `If-None-Match` already matched the image's ETag; nothing to send.
+    NotModified,
+    Found(PictureBody),
+}
+
+pub struct PictureBody {
+    pub bytes: Vec<u8>,
+    pub content_type: String,
+    pub etag: String,
+    pub content_length: u64,
+}
+
+/// Name of the per-partition directory holding metadata sidecars, sibling to the hash
+/// directories it describes. Kept out of `read_dir`-based listing/counting.
+const METADATA_DIR: &str = ".metadata";
+
+/// Sidecar written next to every stored image, so that its original format, size and
+/// scheme -> filename map don't have to be rediscovered by globbing/`read_dir` on every
+/// request.
+#[derive(Deserialize, Serialize)]
+pub struct PictureMetadata {
+    pub original_format_ext: String,
+    pub original_format_mime: String,
+    pub size: u64,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    /// Key: scheme name. Value: the stored file name, e.g. `origin.png`.
+    pub files: HashMap<String, String>,
+}
+
+/// Name of the per-partition index file, sibling to the hash directories it tracks.
+const INDEX_FILE: &str = ".index.json";
+
+/// Persistent, ordered record of every hash stored in a partition, in the order they were
+/// stored; hashes backfilled by a directory scan come first, sorted by name. Backs
+/// `list`'s pagination so page boundaries don't depend on the filesystem's (unstable)
+/// `read_dir` order, and so `total` doesn't require rescanning the directory.
+#[derive(Deserialize, Serialize, Default)]
+struct PartitionIndex {
+    hashes: Vec<String>,
+}
+
+/// Adapts a fully-buffered read into `AsyncRead`, for backends whose `get_picture`
+/// only exposes whole-object reads (no native streaming API).
+struct BufferReader(Cursor<Vec<u8>>);
+
+impl AsyncRead for BufferReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = std::io::Read::read(&mut self.0, buf.initialize_unfilled())?;
+        buf.advance(filled);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_trait]
 pub trait Storage {
     /// Store the compressed output to a storage, an error will be returned if it fails.
-    fn store(&mut self, output: Output) -> Result<Scheme, Box<dyn Error>>;
+    async fn store(&mut self, output: Output) -> Result<Scheme, Box<dyn Error>>;
 
     /// Find a image, if everything goes well, the first element is the bytes Vec, the second element is
     /// the mime type of this file.
-    fn get_picture(
+    async fn get_picture(
         &self,
         partition: &str,
         hash: &str,
         scheme: &str,
     ) -> Result<(Vec<u8>, String), Box<dyn Error>>;
 
+    /// Like `get_picture`, but honors `If-None-Match`. A stored image's hash never changes
+    /// once written, so it doubles as a stable ETag: when `if_none_match` already matches it,
+    /// this returns `PictureResponse::NotModified` instead of re-reading the file. The ETag is
+    /// scoped to `hash`-`scheme`, since different schemes of the same hash are different
+    /// byte payloads (origin vs. thumbnail vs. webp variants).
+    async fn get_picture_conditional(
+        &self,
+        partition: &str,
+        hash: &str,
+        scheme: &str,
+        if_none_match: Option<&str>,
+    ) -> Result<PictureResponse, Box<dyn Error>> {
+        let etag = format!("\"{hash}-{scheme}\"");
+        if if_none_match == Some(etag.as_str()) {
+            return Ok(PictureResponse::NotModified);
+        }
+        let (bytes, content_type) = self.get_picture(partition, hash, scheme).await?;
+        Ok(PictureResponse::Found(PictureBody {
+            content_length: bytes.len() as u64,
+            bytes,
+            content_type,
+            etag,
+        }))
+    }
+
+    /// Like `get_picture`, but yields the bytes as a stream instead of buffering the whole
+    /// file in memory. Backends without a native streaming read fall back to buffering.
+    async fn get_picture_stream(
+        &self,
+        partition: &str,
+        hash: &str,
+        scheme: &str,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, Box<dyn Error>> {
+        let (bytes, _) = self.get_picture(partition, hash, scheme).await?;
+        Ok(Box::pin(BufferReader(Cursor::new(bytes))))
+    }
+
     /// Determine whether a image exists, and returns None if it does not, or returns an struct Pictures.
-    fn exists(&self, partition: &str, id: &str) -> Option<Scheme>;
+    async fn exists(&self, partition: &str, id: &str) -> Option<Scheme>;
 
     /// Delete a image.
-    fn delete(&mut self, partition: &str, hash: &str) -> Result<(), String>;
+    async fn delete(&mut self, partition: &str, hash: &str) -> Result<(), String>;
 
     /// List all schemes in a certain partition.
     /// current >= 1.
-    fn list(
+    async fn list(
         &self,
         current: usize,
         page_size: usize,
         partition: &str,
     ) -> Result<PageList<Scheme>, Box<dyn Error>>;
+
+    /// The app config this backend was constructed with, needed by `import_dir` to run
+    /// imported bytes through the same processing pipeline a regular upload uses.
+    fn config(&self) -> &'static Config;
+
+    /// Walk `source`, detect image files by extension, and ingest each one into `partition`
+    /// through the normal `store` pipeline. Non-image files and hashes already present in
+    /// the partition are skipped; a single bad file is recorded in the summary rather than
+    /// aborting the rest of the batch.
+    async fn import_dir(
+        &mut self,
+        source: &Path,
+        partition: &str,
+    ) -> Result<ImportSummary, Box<dyn Error>> {
+        let mut summary = ImportSummary::default();
+        let mut entries = tokio::fs::read_dir(source).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let is_file = entry
+                .file_type()
+                .await
+                .map(|t| t.is_file())
+                .unwrap_or(false);
+            if !is_file {
+                continue;
+            }
+            let extension = match path.extension().and_then(|e| e.to_str()) {
+                Some(ext) => ext.to_ascii_lowercase(),
+                None => {
+                    summary.skipped.push(path);
+                    continue;
+                }
+            };
+            if !IMPORT_EXTENSIONS.contains(&extension.as_str()) {
+                summary.skipped.push(path);
+                continue;
+            }
+            match import_one(self, &path, partition).await {
+                Ok(true) => summary.succeeded.push(path),
+                Ok(false) => summary.skipped.push(path),
+                Err(e) => summary.failed.push((path, e.to_string())),
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Reads and stores a single image for `import_dir`, through the same processing pipeline a
+/// regular upload uses (so imported images end up with the full set of configured scheme
+/// variants, not just an `origin` entry). Returns `Ok(false)` when the image's content hash
+/// is already present in the partition, rather than erroring.
+async fn import_one<S: Storage + ?Sized>(
+    storage: &mut S,
+    path: &Path,
+    partition: &str,
+) -> Result<bool, Box<dyn Error>> {
+    let bytes = tokio::fs::read(path).await?;
+    let output = crate::pipeline::process_upload(bytes, partition.to_string(), storage.config())?;
+    if storage.exists(partition, &output.hash).await.is_some() {
+        return Ok(false);
+    }
+    storage.store(output).await?;
+    Ok(true)
 }
 
 /// Store images in local file system.
 pub struct Local {
     root_dir: PathBuf,
     config: &'static Config,
-
-    /// How many images a partition have.
-    /// Key: partition string.
-    /// Value: count.
-    count: HashMap<String, usize>,
 }
 
 impl Local {
     pub fn new(mut root_dir: PathBuf, config: &'static Config) -> Self {
-        let mut count = HashMap::new();
-        // Recounting when the app is restarted.
         for key in config.partitions.keys() {
             root_dir.push(key);
             if !root_dir.exists() {
                 create_dir_all(&root_dir).unwrap();
             }
-            let reader = read_dir(&root_dir).unwrap();
-            count.insert(key.to_string(), reader.count());
             root_dir.pop();
         }
-        Local {
-            root_dir,
-            config,
-            count,
-        }
+        Local { root_dir, config }
     }
 
     pub fn try_from_str(value: String, config: &'static Config) -> Result<Local, String> {
@@ -109,29 +281,306 @@ impl Local {
         }
         Ok(Local::new(path, config))
     }
+
+    fn metadata_path(&self, partition: &str, hash: &str) -> PathBuf {
+        let mut path = self.root_dir.clone();
+        path.push(partition);
+        path.push(METADATA_DIR);
+        path.push(format!("{hash}.json"));
+        path
+    }
+
+    /// Reads the metadata sidecar for an image, if one was written for it.
+    async fn read_metadata(&self, partition: &str, hash: &str) -> Option<PictureMetadata> {
+        let bytes = tokio::fs::read(self.metadata_path(partition, hash))
+            .await
+            .ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    async fn write_metadata(
+        &self,
+        partition: &str,
+        hash: &str,
+        metadata: &PictureMetadata,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = self.metadata_path(partition, hash);
+        tokio::fs::create_dir_all(path.parent().ok_or("Invalid metadata path")?).await?;
+        tokio::fs::write(path, serde_json::to_vec(metadata)?).await?;
+        Ok(())
+    }
+
+    fn index_path(&self, partition: &str) -> PathBuf {
+        let mut path = self.root_dir.clone();
+        path.push(partition);
+        path.push(INDEX_FILE);
+        path
+    }
+
+    /// Reads the partition's index, backfilling it from a one-time directory scan whenever
+    /// it's missing OR unreadable (e.g. truncated by a write interrupted mid-flight) — a
+    /// corrupt index must never be treated as "empty", or the next `store()` would overwrite
+    /// it with just the new hash and silently drop every previously stored one.
+    async fn read_index(&self, partition: &str) -> PartitionIndex {
+        let parsed = tokio::fs::read(self.index_path(partition))
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok());
+        match parsed {
+            Some(index) => index,
+            None => {
+                let index = self.scan_partition_index(partition).await;
+                let _ = self.write_index(partition, &index).await;
+                index
+            }
+        }
+    }
+
+    /// Builds a `PartitionIndex` by walking `partition`'s hash directories directly, skipping
+    /// the `.metadata` and `.index.json` sidecars. Used only to seed a missing or unreadable
+    /// index.
+    async fn scan_partition_index(&self, partition: &str) -> PartitionIndex {
+        let mut dir = self.root_dir.clone();
+        dir.push(partition);
+        let mut hashes = vec![];
+        if let Ok(mut entries) = tokio::fs::read_dir(&dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let file_name = entry.file_name();
+                if file_name == METADATA_DIR || file_name == INDEX_FILE {
+                    continue;
+                }
+                let is_dir = entry
+                    .file_type()
+                    .await
+                    .map(|t| t.is_dir())
+                    .unwrap_or(false);
+                if !is_dir {
+                    continue;
+                }
+                if let Some(hash) = file_name.to_str() {
+                    hashes.push(hash.to_string());
+                }
+            }
+        }
+        hashes.sort();
+        PartitionIndex { hashes }
+    }
+
+    /// Writes the index via a temp file + rename so a crash or kill mid-write can never leave
+    /// `.index.json` truncated/invalid — `rename` is atomic, so readers only ever see the
+    /// fully-written old or new contents, never a half-written one.
+    async fn write_index(
+        &self,
+        partition: &str,
+        index: &PartitionIndex,
+    ) -> Result<(), Box<dyn Error>> {
+        let path = self.index_path(partition);
+        let mut tmp_name = path.clone().into_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_name);
+        tokio::fs::write(&tmp_path, serde_json::to_vec(index)?).await?;
+        tokio::fs::rename(&tmp_path, &path).await?;
+        Ok(())
+    }
 }
 
-pub struct Cos {}
+/// Store images in an S3-compatible object storage (Tencent COS, MinIO, AWS S3, ...).
+pub struct Cos {
+    bucket: Box<Bucket>,
+    config: &'static Config,
+
+    /// When set, `pictures` URLs point directly at this public bucket/CDN base
+    /// instead of being proxied through `generate_url`.
+    public_url: Option<String>,
+}
+
+impl Cos {
+    pub fn try_from_self(value: &CosConfig, config: &'static Config) -> Result<Self, String> {
+        let region = Region::Custom {
+            region: value.region.clone(),
+            endpoint: value.endpoint.clone(),
+        };
+        let credentials = Credentials::new(
+            Some(&value.access_key_id),
+            Some(&value.secret_access_key),
+            None,
+            None,
+            None,
+        )
+        .map_err(|e| format!("Failed to build COS credentials: {e}"))?;
+        let bucket = Bucket::new(&value.bucket, region, credentials)
+            .map_err(|e| format!("Failed to connect to COS bucket [{}]: {e}", value.bucket))?
+            .with_path_style();
+        Ok(Cos {
+            bucket,
+            config,
+            public_url: value.public_url.clone(),
+        })
+    }
+
+    fn object_key(partition: &str, hash: &str, name: &str, ext: &str) -> String {
+        format!("/{partition}/{hash}/{name}.{ext}")
+    }
+
+    fn picture_url(&self, partition: &str, name: &str, hash: &str, ext: &str) -> String {
+        match &self.public_url {
+            Some(base) => {
+                let base = base.trim_end_matches('/');
+                format!("{base}/{partition}/{hash}/{name}.{ext}")
+            }
+            None => {
+                let mut base_url = self.config.base_url.clone();
+                if base_url.ends_with('/') {
+                    base_url.remove(base_url.len() - 1);
+                }
+                generate_url(base_url.as_str(), partition, name, hash)
+            }
+        }
+    }
+}
 
+#[async_trait]
 impl Storage for Cos {
-    fn store(&mut self, _: Output) -> Result<Scheme, Box<dyn Error>> {
-        Err("Not implemented.".into())
+    async fn store(&mut self, output: Output) -> Result<Scheme, Box<dyn Error>> {
+        let mut pics = Pictures::new();
+        for target in output.targets {
+            info!("UPLOADING: [{}]", target.name);
+            let (bytes, ext) = match target.file {
+                TargetFile::Original(bytes) => (bytes, output.original_format.ext.clone()),
+                TargetFile::Processed(webp) => (webp, "webp".to_string()),
+            };
+            let key = Self::object_key(&output.partition, &output.hash, &target.name, &ext);
+            self.bucket.put_object(&key, &bytes).await?;
+            pics.insert(
+                target.name.clone(),
+                self.picture_url(
+                    output.partition.as_str(),
+                    target.name.as_str(),
+                    output.hash.as_str(),
+                    ext.as_str(),
+                ),
+            );
+        }
+        let thumbnail = get_thumbnail_name(self.config, output.partition.as_str());
+        Ok(Scheme {
+            id: output.hash,
+            thumbnail,
+            pictures: pics,
+        })
+    }
+
+    async fn get_picture(
+        &self,
+        partition: &str,
+        hash: &str,
+        scheme: &str,
+    ) -> Result<(Vec<u8>, String), Box<dyn Error>> {
+        let prefix = format!("/{partition}/{hash}/{scheme}.");
+        let key = self
+            .bucket
+            .list(prefix, Some("/".to_string()))
+            .await?
+            .into_iter()
+            .flat_map(|listing| listing.contents)
+            .next()
+            .ok_or("Not found")?
+            .key;
+        let extension = key.rsplit('.').next().ok_or("Unknown extension.")?;
+        let format = ImageFormat::try_from(
+            image::ImageFormat::from_extension(extension).ok_or("Unknown extension.")?,
+        )?;
+        let response = self.bucket.get_object(&key).await?;
+        Ok((response.bytes().to_vec(), format.mime_type))
     }
 
-    fn get_picture(&self, _: &str, _: &str, _: &str) -> Result<(Vec<u8>, String), Box<dyn Error>> {
-        Err("Not implemented.".into())
+    async fn exists(&self, partition: &str, id: &str) -> Option<Scheme> {
+        let prefix = format!("/{partition}/{id}/");
+        let results = self.bucket.list(prefix, None).await.ok()?;
+        let mut pics = Pictures::new();
+        for listing in results {
+            for object in listing.contents {
+                let file_name = object.key.rsplit('/').next()?;
+                let (name, ext) = parse_picture_name(file_name)?;
+                pics.insert(name.to_string(), self.picture_url(partition, name, id, ext));
+            }
+        }
+        if pics.is_empty() {
+            return None;
+        }
+        Some(Scheme {
+            id: id.to_string(),
+            thumbnail: get_thumbnail_name(self.config, partition),
+            pictures: pics,
+        })
     }
 
-    fn exists(&self, _: &str, _: &str) -> Option<Scheme> {
-        None
+    async fn delete(&mut self, partition: &str, hash: &str) -> Result<(), String> {
+        let prefix = format!("/{partition}/{hash}/");
+        let results = self
+            .bucket
+            .list(prefix, None)
+            .await
+            .map_err(|e| format!("Failed to list COS objects: {e}"))?;
+        let mut deleted_any = false;
+        for listing in results {
+            for object in listing.contents {
+                self.bucket
+                    .delete_object(&object.key)
+                    .await
+                    .map_err(|e| format!("Delete failed: {e}"))?;
+                deleted_any = true;
+            }
+        }
+        if deleted_any {
+            Ok(())
+        } else {
+            Err("File not found!".to_string())
+        }
     }
 
-    fn delete(&mut self, _: &str, _: &str) -> Result<(), String> {
-        Err("Not implemented".into())
+    async fn list(
+        &self,
+        current: usize,
+        page_size: usize,
+        partition: &str,
+    ) -> Result<PageList<Scheme>, Box<dyn Error>> {
+        let prefix = format!("/{partition}/");
+        let results = self
+            .bucket
+            .list(prefix.clone(), Some("/".to_string()))
+            .await?;
+        let mut hashes: Vec<String> = results
+            .iter()
+            .flat_map(|listing| listing.common_prefixes.clone().unwrap_or_default())
+            .map(|common| {
+                common
+                    .prefix
+                    .trim_start_matches(prefix.as_str())
+                    .trim_end_matches('/')
+                    .to_string()
+            })
+            .collect();
+        hashes.sort();
+        let total = hashes.len();
+        let n = (current - 1) * page_size;
+        let mut list = vec![];
+        for hash in hashes.into_iter().skip(n).take(page_size) {
+            if let Some(scheme) = self.exists(partition, &hash).await {
+                list.push(scheme);
+            }
+        }
+        Ok(PageList {
+            list,
+            pagination: Pagination {
+                current,
+                page_size,
+                total,
+            },
+        })
     }
 
-    fn list(&self, _: usize, _: usize, _: &str) -> Result<PageList<Scheme>, Box<dyn Error>> {
-        Err("Not implemented".into())
+    fn config(&self) -> &'static Config {
+        self.config
     }
 }
 
@@ -158,30 +607,42 @@ fn get_thumbnail_name(config: &'static Config, partition_str: &str) -> String {
     .to_string()
 }
 
+#[async_trait]
 impl Storage for Local {
-    fn store(&mut self, output: Output) -> Result<Scheme, Box<dyn Error>> {
+    async fn store(&mut self, output: Output) -> Result<Scheme, Box<dyn Error>> {
         let config = self.config;
         let mut root_dir = self.root_dir.clone();
         let mut pics = Pictures::new();
+        let mut files = HashMap::new();
+        let mut size: u64 = 0;
         root_dir.push(&output.partition);
         root_dir.push(&output.hash);
-        create_dir_all(&root_dir)?;
+        tokio::fs::create_dir_all(&root_dir).await?;
         for target in output.targets {
             info!("WRITING: [{}]", target.name);
+            let file_name = match &target.file {
+                TargetFile::Original(_) => format!("{}.{}", target.name, output.original_format.ext),
+                TargetFile::Processed(_) => format!("{}.webp", target.name),
+            };
             match target.file {
                 TargetFile::Original(bytes) => {
-                    root_dir.push(&format!("{}.{}", target.name, output.original_format.ext));
-                    let file = File::create(&root_dir)?;
+                    size += bytes.len() as u64;
+                    root_dir.push(&file_name);
+                    let file = File::create(&root_dir).await?;
                     let mut writer = BufWriter::new(file);
-                    writer.write_all(&bytes)?;
+                    writer.write_all(&bytes).await?;
+                    writer.flush().await?;
                 }
                 TargetFile::Processed(webp) => {
-                    root_dir.push(&format!("{}.webp", target.name));
-                    let file = File::create(&root_dir)?;
+                    size += webp.len() as u64;
+                    root_dir.push(&file_name);
+                    let file = File::create(&root_dir).await?;
                     let mut writer = BufWriter::new(file);
-                    writer.write_all(&webp)?;
+                    writer.write_all(&webp).await?;
+                    writer.flush().await?;
                 }
             }
+            files.insert(target.name.clone(), file_name);
             let mut base_url = config.base_url.clone();
             if base_url.ends_with('/') {
                 base_url.remove(base_url.len() - 1);
@@ -197,9 +658,27 @@ impl Storage for Local {
             );
             root_dir.pop();
         }
-        let old = self.count.get(&output.partition).ok_or("Not found")?;
         let thumbnail = get_thumbnail_name(config, output.partition.as_str());
-        self.count.insert(output.partition, old + 1);
+
+        let created_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let metadata = PictureMetadata {
+            original_format_ext: output.original_format.ext.clone(),
+            original_format_mime: output.original_format.mime_type.clone(),
+            size,
+            created_at,
+            files,
+        };
+        self.write_metadata(&output.partition, &output.hash, &metadata)
+            .await?;
+
+        let mut index = self.read_index(&output.partition).await;
+        if !index.hashes.contains(&output.hash) {
+            index.hashes.push(output.hash.clone());
+        }
+        self.write_index(&output.partition, &index).await?;
 
         Ok(Scheme {
             id: output.hash.to_string(),
@@ -208,7 +687,7 @@ impl Storage for Local {
         })
     }
 
-    fn get_picture(
+    async fn get_picture(
         &self,
         partition: &str,
         hash: &str,
@@ -217,9 +696,14 @@ impl Storage for Local {
         let mut dir = self.root_dir.clone();
         dir.push(partition);
         dir.push(hash);
-        dir.push(&format!("{}.*", scheme));
-        let pattern = dir.to_str().unwrap_or("");
-        dir = glob::glob(pattern)?.next().ok_or("Not found")??;
+        dir = match self.read_metadata(partition, hash).await {
+            Some(metadata) => dir.join(metadata.files.get(scheme).ok_or("Not found")?),
+            None => {
+                dir.push(&format!("{}.*", scheme));
+                let pattern = dir.to_str().unwrap_or("");
+                glob::glob(pattern)?.next().ok_or("Not found")??
+            }
+        };
         let extension = dir
             .extension()
             .ok_or("")?
@@ -228,21 +712,55 @@ impl Storage for Local {
         let format = ImageFormat::try_from(
             image::ImageFormat::from_extension(extension).ok_or("Unknown extension.")?,
         )?;
-        let file = File::open(dir)?;
+        let file = File::open(dir).await?;
         let mut reader = BufReader::new(file);
-        let mut buf: Vec<u8> = Vec::with_capacity(reader.capacity());
-        reader.read_to_end(&mut buf)?;
+        let mut buf: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut buf).await?;
         Ok((buf, format.mime_type))
     }
 
-    fn exists(&self, partition: &str, id: &str) -> Option<Scheme> {
+    async fn get_picture_stream(
+        &self,
+        partition: &str,
+        hash: &str,
+        scheme: &str,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, Box<dyn Error>> {
         let mut dir = self.root_dir.clone();
         dir.push(partition);
-        dir.push(id);
+        dir.push(hash);
+        let dir = match self.read_metadata(partition, hash).await {
+            Some(metadata) => dir.join(metadata.files.get(scheme).ok_or("Not found")?),
+            None => {
+                dir.push(&format!("{}.*", scheme));
+                let pattern = dir.to_str().unwrap_or("");
+                glob::glob(pattern)?.next().ok_or("Not found")??
+            }
+        };
+        let file = File::open(dir).await?;
+        Ok(Box::pin(file))
+    }
+
+    async fn exists(&self, partition: &str, id: &str) -> Option<Scheme> {
         let mut result = Pictures::new();
-        let dir = read_dir(dir).ok()?;
-        for res in dir {
-            let entry = res.ok()?;
+        if let Some(metadata) = self.read_metadata(partition, id).await {
+            for name in metadata.files.keys() {
+                result.insert(
+                    name.to_string(),
+                    generate_url(&self.config.base_url, partition, name, id),
+                );
+            }
+            return Some(Scheme {
+                id: id.to_string(),
+                thumbnail: get_thumbnail_name(self.config, partition),
+                pictures: result,
+            });
+        }
+
+        let mut dir = self.root_dir.clone();
+        dir.push(partition);
+        dir.push(id);
+        let mut dir = tokio::fs::read_dir(dir).await.ok()?;
+        while let Ok(Some(entry)) = dir.next_entry().await {
             let file_name = entry.file_name();
             let file_name = file_name.to_str()?;
             let (name, _) = parse_picture_name(file_name)?;
@@ -258,69 +776,66 @@ impl Storage for Local {
         })
     }
 
-    fn delete(&mut self, partition: &str, hash: &str) -> Result<(), String> {
+    async fn delete(&mut self, partition: &str, hash: &str) -> Result<(), String> {
         let mut dir = self.root_dir.clone();
         dir.push(partition);
         dir.push(hash);
         let pattern = dir.to_str().unwrap_or("");
         if let Ok(mut paths) = glob::glob(pattern) {
             if let Some(Ok(path)) = paths.next() {
-                if fs::remove_dir_all(&path).is_err() {
+                if tokio::fs::remove_dir_all(&path).await.is_err() {
                     return Err("Delete failed.".to_string());
                 }
-                let old = self.count.get(partition).ok_or("Not found")?;
-                self.count.insert(partition.to_string(), old - 1);
+                let _ = tokio::fs::remove_file(self.metadata_path(partition, hash)).await;
+                let mut index = self.read_index(partition).await;
+                index.hashes.retain(|h| h != hash);
+                self.write_index(partition, &index)
+                    .await
+                    .map_err(|e| format!("Failed to update index: {e}"))?;
                 return Ok(());
             }
         }
         Err("File not found!".to_string())
     }
 
-    fn list(
+    async fn list(
         &self,
         current: usize,
         page_size: usize,
         partition: &str,
     ) -> Result<PageList<Scheme>, Box<dyn Error>> {
-        let mut dir = self.root_dir.clone();
-        dir.push(partition);
-        let dir = read_dir(dir)?;
+        let mut index = self.read_index(partition).await;
         let n = (current - 1) * page_size;
-        let mut skip = dir.skip(n);
-        let mut list: Vec<Scheme> = vec![];
-        for _ in 0..page_size {
-            if let Some(Ok(res)) = skip.next() {
-                let file_name = res.file_name();
-                let id = file_name.to_str().ok_or("Cannot take the file name.")?;
-                let mut pics = Pictures::new();
-                for item in read_dir(res.path())? {
-                    let item = item?;
-                    let file_name = item.file_name();
-                    let file_name = file_name.to_str().unwrap_or("");
-                    let (name, _) = parse_picture_name(file_name)
-                        .ok_or(format!("File name error: {}", file_name))?;
-
-                    pics.insert(
-                        name.to_string(),
-                        generate_url(&self.config.base_url, partition, name, id),
-                    );
-                }
-                list.push(Scheme {
-                    id: id.to_string(),
-                    thumbnail: get_thumbnail_name(self.config, partition),
-                    pictures: pics,
-                });
-            } else {
-                break;
+        let mut list = vec![];
+        let mut dangling = vec![];
+        for hash in index.hashes.iter().skip(n).take(page_size) {
+            match self.exists(partition, hash).await {
+                Some(scheme) => list.push(scheme),
+                // The index says this hash exists but the files don't (e.g. `delete`'s
+                // directory removal succeeded but its index update didn't land). Prune it so
+                // `total` and future pages stop counting a hash that's no longer there.
+                None => dangling.push(hash.clone()),
             }
         }
+        let total = if dangling.is_empty() {
+            index.hashes.len()
+        } else {
+            index.hashes.retain(|h| !dangling.contains(h));
+            let total = index.hashes.len();
+            let _ = self.write_index(partition, &index).await;
+            total
+        };
         Ok(PageList {
             list,
             pagination: Pagination {
                 current,
                 page_size,
-                total: self.count[partition],
+                total,
             },
         })
     }
+
+    fn config(&self) -> &'static Config {
+        self.config
+    }
 }